@@ -16,20 +16,54 @@
 // relating to use of the SAFE Network Software.
 
 use time;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::prelude::*;
-use std::path;
+use std::path::{self, Path};
 use std::env;
+use std::thread;
 use rustc_serialize::json;
 use std::io;
-use itertools::Itertools;
-use config_utils::Contacts;
+use std::net::IpAddr;
+use std::time::Duration;
+use config_utils::{Contact, Contacts, LastContact, Outcome};
+use file_lock::FileLock;
+use ip_filter::IpFilter;
+use transport;
 
 const MAX_CONTACTS: usize = 1500;
 
+/// Contacts that have failed more than this many times in a row since their
+/// last success are considered dead and are dropped from the cache.
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+
+/// How many cached contacts `check_bootstrap_contacts` probes per pass, so a
+/// single background refresh can never stall on a large cache.
+const CONTACTS_TO_PROBE_PER_PASS: usize = 20;
+
+/// How long to wait for a single liveness probe to connect.
+fn probe_timeout() -> Duration {
+    Duration::from_millis(2000)
+}
+
+/// Relative priority of a contact for bootstrapping: lower sorts first.
+/// Recently-succeeded contacts come first (most recent success first),
+/// then contacts that have never been tried, then previously-failed
+/// contacts ordered by fewest failures.
+fn rank_key(contact: &Contact) -> (u8, i64, u32) {
+    match contact.last_contact {
+        Some(LastContact { outcome: Outcome::Success, timestamp }) => (0, -timestamp, 0),
+        None => (1, 0, 0),
+        Some(LastContact { outcome: Outcome::Failure, .. }) => (2, 0, contact.failures),
+    }
+}
+
 pub struct BootstrapHandler {
     file_name: String,
     last_updated: time::Tm,
+    ip_filter: IpFilter,
+    /// Statically-configured peers, kept separate from the on-disk cache so
+    /// they are never pruned or aged out with it.
+    config_peers: Contacts,
 }
 
 
@@ -47,7 +81,7 @@ pub fn parse_contacts(buffer: Vec<u8>) -> Option<Contacts> {
 
 
 impl BootstrapHandler {
-    pub fn get_file_name() -> String {
+    fn exe_stem() -> String {
         let path = match env::current_exe() {
                 Ok(exe_path) => exe_path,
                 Err(e) => panic!("Failed to get current exe path: {}", e),
@@ -55,41 +89,108 @@ impl BootstrapHandler {
         let name_with_extension = path.file_name().expect("Unknown filename");
         let name = path::Path::new(name_with_extension).file_stem()
             .expect("Unknown extension");
+        name.to_str().unwrap().to_owned()
+    }
 
+    /// The pre-1.0 cache location: named after the running executable and
+    /// resolved against the process's current working directory. Kept only
+    /// so existing deployments keep using their cache rather than losing it
+    /// outright.
+    fn legacy_file_name(name: &str) -> String {
         let mut filename = String::new();
         filename.push_str("./");
-        filename.push_str(name.to_str().unwrap());
+        filename.push_str(name);
         filename.push_str(".bootstrap.cache");
         filename
     }
 
+    /// The proper per-user config directory for the cache, found
+    /// consistently regardless of the process's working directory.
+    fn config_dir_file_name(name: &str) -> String {
+        let mut dir = env::home_dir().unwrap_or_else(|| path::PathBuf::from("."));
+        if cfg!(target_os = "windows") {
+            dir.push("AppData");
+            dir.push("Roaming");
+        } else if cfg!(target_os = "macos") {
+            dir.push("Library");
+            dir.push("Application Support");
+        } else {
+            dir.push(".config");
+        }
+        dir.push(name);
+        let _ = fs::create_dir_all(&dir);
+        dir.push(format!("{}.bootstrap.cache", name));
+        dir.to_string_lossy().into_owned()
+    }
+
+    pub fn get_file_name() -> String {
+        let name = BootstrapHandler::exe_stem();
+        let preferred = BootstrapHandler::config_dir_file_name(&name);
+        let legacy = BootstrapHandler::legacy_file_name(&name);
+        // An existing exe-relative cache from before the config directory
+        // was used wins for as long as no cache exists at `preferred`; it is
+        // read and written in place and is not moved or copied across.
+        if !Path::new(&preferred).exists() && Path::new(&legacy).exists() {
+            legacy
+        } else {
+            preferred
+        }
+    }
+
     pub fn new() -> BootstrapHandler {
         BootstrapHandler {
             file_name: BootstrapHandler::get_file_name(),
             last_updated: time::now(),
+            ip_filter: IpFilter::default_policy(),
+            config_peers: Contacts::new(),
         }
     }
 
+    /// Applies `ip_filter` to contacts going into and coming out of the
+    /// cache, in place of the default public-only policy.
+    pub fn with_ip_filter(mut self, ip_filter: IpFilter) -> BootstrapHandler {
+        self.ip_filter = ip_filter;
+        self
+    }
+
+    /// Supplies a statically-configured peer list that is always offered
+    /// as a bootstrap fallback alongside the cache, without ever being
+    /// persisted, pruned by `MAX_CONTACTS`, or dropped for failures.
+    pub fn with_config_peers(mut self, peers: Contacts) -> BootstrapHandler {
+        self.config_peers = peers;
+        self
+    }
+
     pub fn get_update_duration() -> time::Duration {
         time::Duration::hours(4)
     }
 
     pub fn update_contacts(&mut self, contacts: Contacts, prune: Contacts) -> io::Result<()> {
         try!(self.insert_contacts(contacts, prune));
-        // TODO(Team) this implementation is missing and should be considered in next planning
         if time::now() > self.last_updated + BootstrapHandler::get_update_duration() {
-            // self.check_bootstrap_contacts();
+            self.check_bootstrap_contacts();
+            self.last_updated = time::now();
         }
         Ok(())
     }
 
+    /// Kicks off a background refresh and returns immediately. The refresh
+    /// itself runs on its own thread (see `probe_bootstrap_contacts`) so a
+    /// large cache, or a handful of slow-to-time-out probes, can never stall
+    /// a caller of `update_contacts`.
+    fn check_bootstrap_contacts(&mut self) {
+        let file_name = self.file_name.clone();
+        let ip_filter = self.ip_filter.clone();
+        let spawned = thread::Builder::new()
+            .name("bootstrap-probe".to_owned())
+            .spawn(move || probe_bootstrap_contacts(&file_name, &ip_filter));
+        if let Err(e) = spawned {
+            println!("Failed to spawn bootstrap probe thread : {:?}.", e);
+        }
+    }
+
     pub fn read_bootstrap_file(&self) -> io::Result<(Contacts)> {
-        let mut file = try!(File::open(&self.file_name));
-        let mut contents = String::new();
-        let _ = try!(file.read_to_string(&mut contents));
-        json::decode(&contents)
-             .map_err(|error| io::Error::new(io::ErrorKind::Other,
-                                             format!("Failed to decode bootstrap file: {}", error)))
+        read_contacts_file(&self.file_name, &self.ip_filter)
     }
 
     #[allow(dead_code)]
@@ -103,14 +204,19 @@ impl BootstrapHandler {
                              .take(n).collect::<Contacts>())
     }
 
-    fn write_bootstrap_file(&mut self, mut contacts: Contacts) -> io::Result<()> {
-        contacts = contacts.clone().into_iter().unique().collect();
-        let mut file = try!(File::create(&self.file_name));
-        try!(write!(&mut file, "{}", json::as_pretty_json(&contacts)));
-        file.sync_all()
+    fn write_bootstrap_file(&mut self, contacts: Contacts) -> io::Result<()> {
+        write_contacts_file(&self.file_name, contacts)
     }
 
-    fn insert_contacts(&mut self, mut contacts: Contacts, prune: Contacts) -> io::Result<()> {
+    fn insert_contacts(&mut self, contacts: Contacts, prune: Contacts) -> io::Result<()> {
+        // Serializes the read-modify-write below against other processes
+        // sharing this cache file; released when it goes out of scope.
+        let _lock = try!(FileLock::acquire(Path::new(&self.file_name)));
+
+        let contacts: Contacts = contacts.into_iter()
+            .filter(|contact| self.ip_filter.allows_ip(&contact.address.ip))
+            .collect();
+
         let mut bootstrap_contacts = self.read_bootstrap_file()
             .unwrap_or_else(|e| {
                 println!("Failed to read Bootstrap cache file : {:?} ; {:?} ; Creating New file.",
@@ -119,30 +225,206 @@ impl BootstrapHandler {
             });
 
         if prune.len() > 0 {
-            bootstrap_contacts.retain(|x| !prune.contains(&x));
+            bootstrap_contacts.retain(|x| !prune.iter().any(|p| p.address.ip == x.address.ip));
         }
 
-        contacts.retain(|x| !bootstrap_contacts.contains(&x));
-
-        if bootstrap_contacts.len() == 0usize {
-            bootstrap_contacts = contacts;
-        } else {
-            loop {
-                if (bootstrap_contacts.len() < MAX_CONTACTS) && (!contacts.is_empty()) {
-                    bootstrap_contacts.insert(0usize, contacts.remove(0usize));
-                } else {
-                    break;
+        // A node that reconnects from a new port shows up here with the same
+        // IP but a newer `instance`; refresh its address in place instead of
+        // treating it as a brand-new peer and losing its liveness history.
+        let mut fresh = Contacts::new();
+        for incoming in contacts {
+            let existing = bootstrap_contacts.iter_mut()
+                .find(|c| c.address.ip == incoming.address.ip);
+            match existing {
+                Some(contact) => {
+                    if incoming.address.instance > contact.address.instance {
+                        contact.address = incoming.address;
+                    }
                 }
+                None => fresh.push(incoming),
             }
         }
 
-        self.write_bootstrap_file(bootstrap_contacts)
+        // Merge the fresh candidates in ahead of the existing contacts, then
+        // keep only the best `MAX_CONTACTS` by rank. This evicts the
+        // lowest-scored existing entries to make room rather than simply
+        // refusing fresh, possibly better, contacts once the cache is full.
+        let mut merged = fresh;
+        merged.append(&mut bootstrap_contacts);
+        merged.sort_by(|a, b| rank_key(a).cmp(&rank_key(b)));
+        merged.truncate(MAX_CONTACTS);
+
+        self.write_bootstrap_file(merged)
     }
 
     pub fn get_serialised_contacts(&self) -> io::Result<(Vec<u8>)> {
-        let contacts = try!(self.read_bootstrap_file());
+        let mut contacts = try!(self.read_bootstrap_file());
+        self.append_config_peers(&mut contacts);
         Ok(serialise_contacts(contacts))
     }
+
+    /// Adds any configured peer not already present (by IP) to `contacts`.
+    fn append_config_peers(&self, contacts: &mut Contacts) {
+        let additions = self.missing_config_peers(contacts);
+        contacts.extend(additions);
+    }
+
+    /// The configured peers not already present (by IP) in `contacts`.
+    fn missing_config_peers(&self, contacts: &Contacts) -> Contacts {
+        self.config_peers.iter()
+            .filter(|peer| !contacts.iter().any(|c| c.address.ip == peer.address.ip))
+            .cloned()
+            .collect()
+    }
+
+    /// Records a successful bootstrap attempt against `contact`, resetting
+    /// its failure count and refreshing its last-contact timestamp.
+    pub fn report_success(&mut self, contact: &Contact) -> io::Result<()> {
+        self.update_contact_outcome(contact.address.ip, Outcome::Success)
+    }
+
+    /// Records a failed bootstrap attempt against `contact`, bumping its
+    /// consecutive failure count.
+    pub fn report_failure(&mut self, contact: &Contact) -> io::Result<()> {
+        self.update_contact_outcome(contact.address.ip, Outcome::Failure)
+    }
+
+    fn update_contact_outcome(&mut self,
+                               ip: IpAddr,
+                               outcome: Outcome)
+                               -> io::Result<()> {
+        apply_contact_outcome(&self.file_name, &self.ip_filter, ip, outcome)
+    }
+
+    /// Returns up to `n` contacts ordered by bootstrap priority: contacts
+    /// with a recent successful connection first (most recent first), then
+    /// never-tried contacts, then previously-failed contacts ordered by
+    /// fewest failures. Contacts that have failed too many times in a row
+    /// are dropped entirely.
+    pub fn ranked_contacts(&self, n: usize) -> io::Result<(Contacts)> {
+        let contacts = try!(self.read_bootstrap_file());
+        // A cache entry that happens to share a config peer's IP is dropped
+        // here rather than ranked alongside it, so it can't end up standing
+        // in for the config peer in the truncated-away portion below.
+        let mut ranked: Contacts = contacts.into_iter()
+            .filter(|c| c.failures <= MAX_CONSECUTIVE_FAILURES)
+            .filter(|c| !self.config_peers.iter().any(|peer| peer.address.ip == c.address.ip))
+            .collect();
+        ranked.sort_by(|a, b| rank_key(a).cmp(&rank_key(b)));
+
+        // Config peers are always offered as a fallback, even when the cache
+        // alone already has `n` entries to offer: reserve room for them (up
+        // to `n` in all) instead of truncating them away
+        // behind a cache full of stale or low-quality contacts.
+        let reserved = self.config_peers.len().min(n);
+        ranked.truncate(n.saturating_sub(reserved));
+        ranked.extend(self.config_peers.iter().cloned().take(reserved));
+        Ok(ranked)
+    }
+}
+
+fn read_contacts_file(file_name: &str, ip_filter: &IpFilter) -> io::Result<Contacts> {
+    let mut file = try!(File::open(file_name));
+    let mut contents = String::new();
+    let _ = try!(file.read_to_string(&mut contents));
+    let contacts: Contacts = try!(json::decode(&contents)
+         .map_err(|error| io::Error::new(io::ErrorKind::Other,
+                                         format!("Failed to decode bootstrap file: {}", error))));
+    // Re-applying the filter on every read means tightening the policy
+    // retroactively hides contacts that were cached under a looser one.
+    Ok(contacts.into_iter()
+               .filter(|contact| ip_filter.allows_ip(&contact.address.ip))
+               .collect())
+}
+
+fn write_contacts_file(file_name: &str, contacts: Contacts) -> io::Result<()> {
+    let mut seen = ::std::collections::HashSet::new();
+    let contacts: Contacts = contacts.into_iter()
+        .filter(|contact| seen.insert(contact.address.ip))
+        .collect();
+
+    // Write to a sibling temp file and rename it into place so a crash or a
+    // concurrent writer never leaves a truncated or empty cache file behind;
+    // a reader always sees either the old or new contents.
+    let tmp_file_name = format!("{}.tmp", file_name);
+    {
+        let mut file = try!(File::create(&tmp_file_name));
+        try!(write!(&mut file, "{}", json::as_pretty_json(&contacts)));
+        try!(file.sync_all());
+    }
+    fs::rename(&tmp_file_name, file_name)
+}
+
+fn apply_contact_outcome(file_name: &str,
+                          ip_filter: &IpFilter,
+                          ip: IpAddr,
+                          outcome: Outcome)
+                          -> io::Result<()> {
+    // Serializes this read-modify-write against `insert_contacts` and
+    // against other processes sharing this cache file, the same as it does
+    // there; without it a concurrent insert can race this update and lose
+    // one side's write.
+    let _lock = try!(FileLock::acquire(Path::new(file_name)));
+    let mut contacts = try!(read_contacts_file(file_name, ip_filter));
+    if let Some(contact) = contacts.iter_mut().find(|c| c.address.ip == ip) {
+        contact.last_contact = Some(LastContact {
+            timestamp: time::get_time().sec,
+            outcome: outcome,
+        });
+        match outcome {
+            Outcome::Success => contact.failures = 0,
+            Outcome::Failure => contact.failures += 1,
+        }
+    }
+    write_contacts_file(file_name, contacts)
+}
+
+/// Probes up to `CONTACTS_TO_PROBE_PER_PASS` cached contacts, starting with
+/// the ones checked longest ago, and feeds each result into its liveness
+/// metadata so persistently unreachable contacts eventually fall out of
+/// `ranked_contacts`. Runs on its own thread, spawned by
+/// `BootstrapHandler::check_bootstrap_contacts`.
+fn probe_bootstrap_contacts(file_name: &str, ip_filter: &IpFilter) {
+    let mut contacts = match read_contacts_file(file_name, ip_filter) {
+        Ok(contacts) => contacts,
+        Err(_) => return,
+    };
+    contacts.sort_by_key(|c| c.last_contact.map(|lc| lc.timestamp).unwrap_or(0));
+
+    for contact in contacts.into_iter().take(CONTACTS_TO_PROBE_PER_PASS) {
+        let endpoint = match contact.endpoint() {
+            Some(endpoint) => endpoint,
+            None => continue,
+        };
+        let outcome = if transport::connect(&endpoint, probe_timeout()).is_ok() {
+            Outcome::Success
+        } else {
+            Outcome::Failure
+        };
+        if let Err(e) = apply_contact_outcome(file_name, ip_filter, contact.address.ip, outcome) {
+            println!("Failed to record bootstrap probe result : {:?} ; {:?}.", file_name, e);
+        }
+    }
+
+    if let Err(e) = prune_dead_contacts(file_name, ip_filter) {
+        println!("Failed to prune dead bootstrap contacts : {:?} ; {:?}.", file_name, e);
+    }
+}
+
+/// Drops contacts that have failed too many probes in a row from the cache
+/// file itself, rather than merely filtering them out of `ranked_contacts`
+/// at read time, so a persistently unreachable peer doesn't sit on disk
+/// forever.
+fn prune_dead_contacts(file_name: &str, ip_filter: &IpFilter) -> io::Result<()> {
+    let _lock = try!(FileLock::acquire(Path::new(file_name)));
+    let mut contacts = try!(read_contacts_file(file_name, ip_filter));
+    let before = contacts.len();
+    contacts.retain(|c| c.failures <= MAX_CONSECUTIVE_FAILURES);
+    if contacts.len() == before {
+        Ok(())
+    } else {
+        write_contacts_file(file_name, contacts)
+    }
 }
 
 #[cfg(test)]
@@ -155,13 +437,47 @@ mod test {
     use rand;
     use std::path::Path;
     use config_utils::{Contact, Contacts};
+    use ip_filter::{self, IpClass, IpFilter, IpPolicy};
 
     use super::MAX_CONTACTS;
+    use super::{MAX_CONSECUTIVE_FAILURES, probe_bootstrap_contacts, read_contacts_file};
+    use time;
+
+    /// Random addresses can otherwise land in a non-global range, which the
+    /// default public-only `IpFilter` would silently drop from the cache.
+    fn random_public_ipv4() -> Ipv4Addr {
+        loop {
+            let ip = Ipv4Addr::new(rand::random::<u8>(), rand::random::<u8>(),
+                                    rand::random::<u8>(), rand::random::<u8>());
+            if ip_filter::classify(&IpAddr::V4(ip)) == IpClass::Global {
+                return ip;
+            }
+        }
+    }
+
+    /// A cache file path unique to this call, so tests that run concurrently
+    /// under `cargo test` never share a file and race each other's reads,
+    /// writes and content assertions.
+    fn unique_file_name() -> String {
+        format!("./bootstrap_handler_test_{}.cache", rand::random::<u64>())
+    }
+
+    /// Builds a handler against `file_name` directly, bypassing
+    /// `BootstrapHandler::get_file_name`'s single exe-derived path so each
+    /// test can use its own cache file.
+    fn test_handler(file_name: String) -> BootstrapHandler {
+        BootstrapHandler {
+            file_name: file_name,
+            last_updated: time::now(),
+            ip_filter: IpFilter::default_policy(),
+            config_peers: Contacts::new(),
+        }
+    }
 
     #[test]
     fn serialisation() {
         let addr = net::SocketAddrV4::new(net::Ipv4Addr::new(1,2,3,4), 8080);
-        let contact  = Contact { endpoint: Endpoint::Tcp(SocketAddr::V4(addr)) };
+        let contact  = Contact::new(Endpoint::Tcp(SocketAddr::V4(addr)));
         let mut contacts = Contacts::new();
         contacts.push(contact.clone());
         contacts.push(contact.clone());
@@ -174,23 +490,16 @@ mod test {
     fn bootstrap_handler_test() {
         let mut contacts = Vec::new();
         for _ in 0..10 {
-            let mut random_addr_0 = Vec::with_capacity(4);
-            random_addr_0.push(rand::random::<u8>());
-            random_addr_0.push(rand::random::<u8>());
-            random_addr_0.push(rand::random::<u8>());
-            random_addr_0.push(rand::random::<u8>());
-
             let port_0: u16 = rand::random::<u16>();
-            let addr_0 = net::SocketAddrV4::new(net::Ipv4Addr::new(random_addr_0[0],
-                random_addr_0[1], random_addr_0[2], random_addr_0[3]), port_0);
-            let new_contact = Contact { endpoint: Endpoint::Tcp(SocketAddr::V4(addr_0)) };
+            let addr_0 = net::SocketAddrV4::new(random_public_ipv4(), port_0);
+            let new_contact = Contact::new(Endpoint::Tcp(SocketAddr::V4(addr_0)));
             contacts.push(new_contact);
         }
 
-        let file_name = BootstrapHandler::get_file_name();
+        let file_name = unique_file_name();
         let path = Path::new(&file_name);
 
-        let mut bootstrap_handler = BootstrapHandler::new();
+        let mut bootstrap_handler = test_handler(file_name.clone());
         let file = fs::File::create(&path);
         assert!(file.is_ok()); // Check whether the database file is created
         // Add Contacts
@@ -216,23 +525,16 @@ mod test {
         let mut contacts = Vec::new();
         let number = 10usize;
         for _ in 0..number {
-            let mut random_addr_0 = Vec::with_capacity(4);
-            random_addr_0.push(rand::random::<u8>());
-            random_addr_0.push(rand::random::<u8>());
-            random_addr_0.push(rand::random::<u8>());
-            random_addr_0.push(rand::random::<u8>());
-
             let port_0: u16 = rand::random::<u16>();
-            let addr_0 = net::SocketAddrV4::new(net::Ipv4Addr::new(random_addr_0[0],
-                random_addr_0[1], random_addr_0[2], random_addr_0[3]), port_0);
-            let new_contact = Contact { endpoint: Endpoint::Tcp(SocketAddr::V4(addr_0)) };
+            let addr_0 = net::SocketAddrV4::new(random_public_ipv4(), port_0);
+            let new_contact = Contact::new(Endpoint::Tcp(SocketAddr::V4(addr_0)));
             contacts.push(new_contact);
         }
 
-        let file_name = BootstrapHandler::get_file_name();
+        let file_name = unique_file_name();
         let path = Path::new(&file_name);
 
-        let mut bootstrap_handler = BootstrapHandler::new();
+        let mut bootstrap_handler = test_handler(file_name.clone());
         let file = fs::File::create(&path);
         assert!(file.is_ok());
 
@@ -268,23 +570,16 @@ mod test {
         let mut contacts = Vec::new();
         let number = 10usize;
         for _ in 0..number {
-            let mut random_addr_0 = Vec::with_capacity(4);
-            random_addr_0.push(rand::random::<u8>());
-            random_addr_0.push(rand::random::<u8>());
-            random_addr_0.push(rand::random::<u8>());
-            random_addr_0.push(rand::random::<u8>());
-
             let port_0: u16 = rand::random::<u16>();
-            let addr_0 = net::SocketAddrV4::new(net::Ipv4Addr::new(random_addr_0[0],
-                random_addr_0[1], random_addr_0[2], random_addr_0[3]), port_0);
-            let new_contact = Contact { endpoint: Endpoint::Tcp(SocketAddr::V4(addr_0)) };
+            let addr_0 = net::SocketAddrV4::new(random_public_ipv4(), port_0);
+            let new_contact = Contact::new(Endpoint::Tcp(SocketAddr::V4(addr_0)));
             contacts.push(new_contact);
         }
 
-        let file_name = BootstrapHandler::get_file_name();
+        let file_name = unique_file_name();
         let path = Path::new(&file_name);
 
-        let mut bootstrap_handler = BootstrapHandler::new();
+        let mut bootstrap_handler = test_handler(file_name.clone());
         let file = fs::File::create(&path);
         assert!(file.is_ok());
 
@@ -317,16 +612,9 @@ mod test {
         assert_eq!(recovered_contacts.len(), number);
 
         // create a new contact...
-        let mut ip = Vec::with_capacity(4);
-
-        ip.push(rand::random::<u8>());
-        ip.push(rand::random::<u8>());
-        ip.push(rand::random::<u8>());
-        ip.push(rand::random::<u8>());
-
-        let port = rand::random::<u16>();
-        let ipport = net::SocketAddrV4::new(Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3]), port);
-        let new_contact = Contact{ endpoint: Endpoint::Tcp(SocketAddr::V4(ipport)) };
+                let port = rand::random::<u16>();
+        let ipport = net::SocketAddrV4::new(random_public_ipv4(), port);
+        let new_contact = Contact::new(Endpoint::Tcp(SocketAddr::V4(ipport)));
         let mut new_contacts = Vec::new();
         new_contacts.push(new_contact.clone());
 
@@ -362,23 +650,16 @@ mod test {
         let half_number = number / 2;
 
         for _ in 0..number {
-            let mut random_addr_0 = Vec::with_capacity(4);
-            random_addr_0.push(rand::random::<u8>());
-            random_addr_0.push(rand::random::<u8>());
-            random_addr_0.push(rand::random::<u8>());
-            random_addr_0.push(rand::random::<u8>());
-
             let port_0: u16 = rand::random::<u16>();
-            let addr_0 = net::SocketAddrV4::new(net::Ipv4Addr::new(random_addr_0[0],
-                random_addr_0[1], random_addr_0[2], random_addr_0[3]), port_0);
-            let new_contact = Contact { endpoint: Endpoint::Tcp(SocketAddr::V4(addr_0)) };
+            let addr_0 = net::SocketAddrV4::new(random_public_ipv4(), port_0);
+            let new_contact = Contact::new(Endpoint::Tcp(SocketAddr::V4(addr_0)));
             contacts.push(new_contact);
         }
 
-        let file_name = BootstrapHandler::get_file_name();
+        let file_name = unique_file_name();
         let path = Path::new(&file_name);
 
-        let mut bootstrap_handler = BootstrapHandler::new();
+        let mut bootstrap_handler = test_handler(file_name.clone());
         let file = fs::File::create(&path);
         assert!(file.is_ok());
 
@@ -409,23 +690,16 @@ mod test {
     fn max_contacts() {
         let mut contacts = Vec::new();
         for _ in 0..MAX_CONTACTS {
-            let mut ip = Vec::with_capacity(4);
-
-            ip.push(rand::random::<u8>());
-            ip.push(rand::random::<u8>());
-            ip.push(rand::random::<u8>());
-            ip.push(rand::random::<u8>());
-
-            let port = rand::random::<u16>();
-            let ipport = net::SocketAddrV4::new(Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3]), port);
-            let contact = Contact{ endpoint: Endpoint::Tcp(SocketAddr::V4(ipport)) };
+                        let port = rand::random::<u16>();
+            let ipport = net::SocketAddrV4::new(random_public_ipv4(), port);
+            let contact = Contact::new(Endpoint::Tcp(SocketAddr::V4(ipport)));
 
             contacts.push(contact);
         }
 
-        let file_name = BootstrapHandler::get_file_name();
+        let file_name = unique_file_name();
         let path = Path::new(&file_name);
-        let mut bootstrap_handler = BootstrapHandler::new();
+        let mut bootstrap_handler = test_handler(file_name.clone());
         let file = fs::File::create(&path);
 
         // check that the file got created...
@@ -440,34 +714,35 @@ mod test {
         assert_eq!(recovered_contacts.len(), MAX_CONTACTS);
 
         // create a new contact...
-        let mut ip = Vec::with_capacity(4);
-
-        ip.push(rand::random::<u8>());
-        ip.push(rand::random::<u8>());
-        ip.push(rand::random::<u8>());
-        ip.push(rand::random::<u8>());
-
-        let port = rand::random::<u16>();
-        let ipport = net::SocketAddrV4::new(Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3]), port);
-        let new_contact = Contact{ endpoint: Endpoint::Tcp(SocketAddr::V4(ipport)) };
+                let port = rand::random::<u16>();
+        let ipport = net::SocketAddrV4::new(random_public_ipv4(), port);
+        let new_contact = Contact::new(Endpoint::Tcp(SocketAddr::V4(ipport)));
         let mut new_contacts = Vec::new();
         new_contacts.push(new_contact.clone());
 
-        // try inserting without also pruning...
+        // try inserting without also pruning: a full cache no longer simply
+        // refuses the newcomer, it evicts the lowest-scored existing entry
+        // (here all tied, so the one that sorts last) to make room...
         assert!(bootstrap_handler.insert_contacts(new_contacts.clone(), Contacts::new()).is_ok());
         let recovered_contacts = bootstrap_handler.read_bootstrap_file().unwrap();
-        // check that the recovered contacts are the same as the originals...
-        assert_eq!(recovered_contacts, contacts);
-        // ...and that the number of contacts is still MAX_CONTACTS...
         assert_eq!(recovered_contacts.len(), MAX_CONTACTS);
+        assert_eq!(recovered_contacts[0], new_contact);
+        assert!(!recovered_contacts.contains(&contacts[MAX_CONTACTS - 1]));
+
+        // create a second new contact...
+        let port = rand::random::<u16>();
+        let ipport = net::SocketAddrV4::new(random_public_ipv4(), port);
+        let second_new_contact = Contact::new(Endpoint::Tcp(SocketAddr::V4(ipport)));
+        let mut second_new_contacts = Vec::new();
+        second_new_contacts.push(second_new_contact.clone());
 
         // get the last contact in the list and prune it from the bootstrap file...
         let prune_contact = recovered_contacts[recovered_contacts.len() - 1].clone();
         let mut prune_contacts = Vec::new();
         prune_contacts.push(prune_contact.clone());
 
-        // insert the new contact again pruning the last entry...
-        assert!(bootstrap_handler.insert_contacts(new_contacts.clone(), prune_contacts.clone()).is_ok());
+        // insert the second new contact while pruning the last entry...
+        assert!(bootstrap_handler.insert_contacts(second_new_contacts.clone(), prune_contacts.clone()).is_ok());
         let recovered_contacts = bootstrap_handler.read_bootstrap_file().unwrap();
 
         // check that the recovered contacts are not the same as the originals...
@@ -477,9 +752,11 @@ mod test {
         // check that the pruned contact is not still at the end of the list...
         let last_contact = recovered_contacts[recovered_contacts.len() - 1].clone();
         assert!(last_contact != prune_contact.clone());
-        // check that the new contact is at the start of the list...
+        // check that the second new contact is at the start of the list...
         let first_contact = recovered_contacts[0].clone();
-        assert_eq!(first_contact, new_contact.clone());
+        assert_eq!(first_contact, second_new_contact.clone());
+        // the earlier new contact survived this second round...
+        assert!(recovered_contacts.contains(&new_contact));
 
         // remove the bootstrap file from disk...
         match fs::remove_file(file_name.clone()) {
@@ -487,4 +764,137 @@ mod test {
             Err(e) => println!("Failed to remove {}: {}", file_name, e),
         };
     }
+
+    #[test]
+    fn ranked_contacts_orders_by_recency_then_never_tried_then_fewest_failures() {
+        let never_tried = Contact::new(Endpoint::Tcp(SocketAddr::V4(
+            net::SocketAddrV4::new(random_public_ipv4(), rand::random::<u16>()))));
+        let succeeded = Contact::new(Endpoint::Tcp(SocketAddr::V4(
+            net::SocketAddrV4::new(random_public_ipv4(), rand::random::<u16>()))));
+        let failed = Contact::new(Endpoint::Tcp(SocketAddr::V4(
+            net::SocketAddrV4::new(random_public_ipv4(), rand::random::<u16>()))));
+
+        let file_name = unique_file_name();
+        let path = Path::new(&file_name);
+        let mut bootstrap_handler = test_handler(file_name.clone());
+        let file = fs::File::create(&path);
+        assert!(file.is_ok());
+
+        let contacts = vec![never_tried.clone(), succeeded.clone(), failed.clone()];
+        assert!(bootstrap_handler.insert_contacts(contacts, Contacts::new()).is_ok());
+        assert!(bootstrap_handler.report_success(&succeeded).is_ok());
+        assert!(bootstrap_handler.report_failure(&failed).is_ok());
+
+        let ranked = bootstrap_handler.ranked_contacts(10).unwrap();
+        let position = |ip| ranked.iter().position(|c| c.address.ip == ip).unwrap();
+        let succeeded_pos = position(succeeded.address.ip);
+        let never_tried_pos = position(never_tried.address.ip);
+        let failed_pos = position(failed.address.ip);
+
+        assert!(succeeded_pos < never_tried_pos);
+        assert!(never_tried_pos < failed_pos);
+
+        match fs::remove_file(file_name.clone()) {
+            Ok(_) => (),
+            Err(e) => println!("Failed to remove {}: {}", file_name, e),
+        };
+    }
+
+    #[test]
+    fn ranked_contacts_reserves_room_for_config_peers_when_cache_is_full() {
+        let config_peer = Contact::new(Endpoint::Tcp(SocketAddr::V4(
+            net::SocketAddrV4::new(random_public_ipv4(), rand::random::<u16>()))));
+
+        let file_name = unique_file_name();
+        let path = Path::new(&file_name);
+        let mut bootstrap_handler = test_handler(file_name.clone())
+            .with_config_peers(vec![config_peer.clone()]);
+        let file = fs::File::create(&path);
+        assert!(file.is_ok());
+
+        let mut cache_contacts = Vec::new();
+        for _ in 0..5 {
+            cache_contacts.push(Contact::new(Endpoint::Tcp(SocketAddr::V4(
+                net::SocketAddrV4::new(random_public_ipv4(), rand::random::<u16>())))));
+        }
+        assert!(bootstrap_handler.insert_contacts(cache_contacts.clone(), Contacts::new()).is_ok());
+
+        // asking for exactly as many contacts as the cache already holds must
+        // still make room for the config peer rather than truncating it away...
+        let ranked = bootstrap_handler.ranked_contacts(cache_contacts.len()).unwrap();
+        assert_eq!(ranked.len(), cache_contacts.len());
+        assert!(ranked.iter().any(|c| c.address.ip == config_peer.address.ip));
+
+        match fs::remove_file(file_name.clone()) {
+            Ok(_) => (),
+            Err(e) => println!("Failed to remove {}: {}", file_name, e),
+        };
+    }
+
+    #[test]
+    fn ranked_contacts_keeps_config_peer_whose_ip_collides_with_a_cache_entry() {
+        let ip = random_public_ipv4();
+        let config_peer = Contact::new(Endpoint::Tcp(SocketAddr::V4(
+            net::SocketAddrV4::new(ip, rand::random::<u16>()))));
+
+        let file_name = unique_file_name();
+        let path = Path::new(&file_name);
+        let mut bootstrap_handler = test_handler(file_name.clone())
+            .with_config_peers(vec![config_peer.clone()]);
+        let file = fs::File::create(&path);
+        assert!(file.is_ok());
+
+        // a cache entry sharing the config peer's IP must not be mistaken for
+        // it and left to be truncated away in its place.
+        let colliding_contact = Contact::new(Endpoint::Tcp(SocketAddr::V4(
+            net::SocketAddrV4::new(ip, rand::random::<u16>()))));
+        let mut cache_contacts = vec![colliding_contact];
+        for _ in 0..4 {
+            cache_contacts.push(Contact::new(Endpoint::Tcp(SocketAddr::V4(
+                net::SocketAddrV4::new(random_public_ipv4(), rand::random::<u16>())))));
+        }
+        assert!(bootstrap_handler.insert_contacts(cache_contacts.clone(), Contacts::new()).is_ok());
+
+        let ranked = bootstrap_handler.ranked_contacts(cache_contacts.len()).unwrap();
+        assert_eq!(ranked.len(), cache_contacts.len());
+        assert!(ranked.iter().any(|c| c.address.ip == config_peer.address.ip &&
+                                       c.address == config_peer.address));
+
+        match fs::remove_file(file_name.clone()) {
+            Ok(_) => (),
+            Err(e) => println!("Failed to remove {}: {}", file_name, e),
+        };
+    }
+
+    #[test]
+    fn probe_records_failures_and_prunes_persistently_dead_contacts() {
+        // 127.0.0.1 on a port nothing is listening on fails fast and
+        // deterministically, without depending on real network access.
+        let port = rand::random::<u16>();
+        let addr = net::SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), port);
+        let dead_contact = Contact::new(Endpoint::Tcp(SocketAddr::V4(addr)));
+
+        let file_name = unique_file_name();
+        let path = Path::new(&file_name);
+        let ip_filter = IpFilter::new(IpPolicy::AllowAll);
+        let mut bootstrap_handler = test_handler(file_name.clone()).with_ip_filter(ip_filter.clone());
+        let file = fs::File::create(&path);
+        assert!(file.is_ok());
+
+        assert!(bootstrap_handler.insert_contacts(vec![dead_contact.clone()], Contacts::new()).is_ok());
+
+        for _ in 0..(MAX_CONSECUTIVE_FAILURES + 1) {
+            probe_bootstrap_contacts(&file_name, &ip_filter);
+        }
+
+        // enough consecutive failed probes drop the contact from the cache
+        // file itself, not merely from `ranked_contacts`...
+        let recovered_contacts = read_contacts_file(&file_name, &ip_filter).unwrap();
+        assert!(recovered_contacts.is_empty());
+
+        match fs::remove_file(file_name.clone()) {
+            Ok(_) => (),
+            Err(e) => println!("Failed to remove {}: {}", file_name, e),
+        };
+    }
 }
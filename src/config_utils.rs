@@ -0,0 +1,223 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use time;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use transport::Endpoint;
+use rustc_serialize::{Decodable, Encodable};
+
+/// Outcome of the most recent bootstrap attempt against a contact.
+#[derive(RustcEncodable, RustcDecodable, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+/// Timestamp and outcome of the most recent contact attempt.
+#[derive(RustcEncodable, RustcDecodable, Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LastContact {
+    pub timestamp: i64,
+    pub outcome: Outcome,
+}
+
+/// Identifies which service a port on a `ContactAddress` belongs to. Tags
+/// are opaque: a reader that doesn't recognise one simply ignores it, which
+/// is what keeps the cache format forward- and backward-compatible as
+/// transports are added or removed.
+pub type SocketTag = u8;
+
+pub const TAG_TCP_ACCEPT: SocketTag = 0;
+pub const TAG_UDP: SocketTag = 1;
+pub const TAG_SERVICE: SocketTag = 2;
+
+/// Where a node can be reached. A node has one IP address and may listen on
+/// several sockets at that address, each identified by a tag rather than by
+/// duplicating the whole contact per transport.
+#[derive(RustcEncodable, RustcDecodable, Debug, Clone, Eq, PartialEq)]
+pub struct ContactAddress {
+    pub ip: IpAddr,
+    pub sockets: BTreeMap<SocketTag, u16>,
+    /// Protocol/cache-format version the node advertises.
+    pub version: u8,
+    /// Wallclock time (seconds since epoch) at which the node instance
+    /// identified by this address last (re)started. A newer instance at the
+    /// same IP supersedes an older one rather than being treated as a
+    /// distinct peer.
+    pub instance: i64,
+}
+
+impl ContactAddress {
+    pub fn new(ip: IpAddr, sockets: BTreeMap<SocketTag, u16>, version: u8) -> ContactAddress {
+        ContactAddress {
+            ip: ip,
+            sockets: sockets,
+            version: version,
+            instance: time::get_time().sec,
+        }
+    }
+
+    /// Builds an address advertising a single TCP-accept socket, the shape
+    /// every contact had before multi-transport support existed.
+    pub fn single_tcp(endpoint: Endpoint) -> ContactAddress {
+        let addr = endpoint.socket_addr();
+        let mut sockets = BTreeMap::new();
+        let _ = sockets.insert(TAG_TCP_ACCEPT, addr.port());
+        ContactAddress {
+            ip: addr.ip(),
+            sockets: sockets,
+            version: 0,
+            instance: time::get_time().sec,
+        }
+    }
+
+    /// The endpoint to dial when only a single, best-effort socket is
+    /// needed. Prefers the TCP-accept socket, falling back to whichever
+    /// socket is advertised.
+    pub fn primary_endpoint(&self) -> Option<Endpoint> {
+        self.sockets.get(&TAG_TCP_ACCEPT)
+            .or_else(|| self.sockets.values().next())
+            .map(|port| Endpoint::Tcp(SocketAddr::new(self.ip, *port)))
+    }
+}
+
+/// A single bootstrap contact, together with the liveness metadata used to
+/// rank it against its peers.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Contact {
+    pub address: ContactAddress,
+    /// When this contact was first added to the cache (seconds since epoch).
+    pub added: i64,
+    /// Consecutive failures since the last success.
+    pub failures: u32,
+    /// Timestamp and outcome of the most recent attempt, if any has been made.
+    pub last_contact: Option<LastContact>,
+}
+
+impl Contact {
+    /// Convenience constructor for the common single-TCP-socket case.
+    pub fn new(endpoint: Endpoint) -> Contact {
+        Contact::with_address(ContactAddress::single_tcp(endpoint))
+    }
+
+    pub fn with_address(address: ContactAddress) -> Contact {
+        Contact {
+            address: address,
+            added: time::get_time().sec,
+            failures: 0,
+            last_contact: None,
+        }
+    }
+
+    pub fn endpoint(&self) -> Option<Endpoint> {
+        self.address.primary_endpoint()
+    }
+}
+
+pub type Contacts = Vec<Contact>;
+
+impl Encodable for Contact {
+    fn encode<S: ::rustc_serialize::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("Contact", 4, |s| {
+            try!(s.emit_struct_field("address", 0, |s| self.address.encode(s)));
+            try!(s.emit_struct_field("added", 1, |s| self.added.encode(s)));
+            try!(s.emit_struct_field("failures", 2, |s| self.failures.encode(s)));
+            try!(s.emit_struct_field("last_contact", 3, |s| self.last_contact.encode(s)));
+            Ok(())
+        })
+    }
+}
+
+impl Decodable for Contact {
+    fn decode<D: ::rustc_serialize::Decoder>(d: &mut D) -> Result<Contact, D::Error> {
+        d.read_struct("Contact", 4, |d| {
+            // Current cache files carry a tagged `address`; files written
+            // before multi-transport support existed carry a single
+            // `endpoint` instead, which is upconverted on the fly.
+            // Fields are read as `Option`s so that a missing one decodes to
+            // `None` instead of an error that leaves the decoder mid-struct.
+            let address = match try!(d.read_struct_field("address", 0,
+                                                         |d| Option::<ContactAddress>::decode(d))) {
+                Some(address) => address,
+                None => {
+                    let endpoint = try!(d.read_struct_field("endpoint", 0, |d| Endpoint::decode(d)));
+                    ContactAddress::single_tcp(endpoint)
+                }
+            };
+            // Older cache files were written before liveness metadata existed;
+            // default any field that is missing rather than failing to decode.
+            let added = try!(d.read_struct_field("added", 1, |d| Option::<i64>::decode(d)))
+                            .unwrap_or_else(|| time::get_time().sec);
+            let failures = try!(d.read_struct_field("failures", 2, |d| Option::<u32>::decode(d)))
+                               .unwrap_or(0);
+            let last_contact = try!(d.read_struct_field("last_contact", 3,
+                                                          |d| Option::<LastContact>::decode(d)));
+            Ok(Contact {
+                address: address,
+                added: added,
+                failures: failures,
+                last_contact: last_contact,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::{self, Ipv4Addr, SocketAddr};
+    use transport::Endpoint;
+    use rustc_serialize::json;
+
+    #[derive(RustcEncodable)]
+    struct LegacyContact {
+        endpoint: Endpoint,
+    }
+
+    #[derive(RustcEncodable)]
+    struct AddressOnlyContact {
+        address: ContactAddress,
+    }
+
+    #[test]
+    fn decodes_legacy_single_endpoint_contacts() {
+        let addr = net::SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 8080);
+        let endpoint = Endpoint::Tcp(SocketAddr::V4(addr));
+        let encoded = json::encode(&LegacyContact { endpoint: endpoint }).unwrap();
+
+        let contact: Contact = json::decode(&encoded).unwrap();
+
+        assert_eq!(contact.address, ContactAddress::single_tcp(endpoint));
+        assert_eq!(contact.failures, 0);
+        assert_eq!(contact.last_contact, None);
+    }
+
+    #[test]
+    fn defaults_missing_liveness_fields() {
+        let addr = net::SocketAddrV4::new(Ipv4Addr::new(5, 6, 7, 8), 9);
+        let endpoint = Endpoint::Tcp(SocketAddr::V4(addr));
+        let address = ContactAddress::single_tcp(endpoint);
+        let encoded = json::encode(&AddressOnlyContact { address: address.clone() }).unwrap();
+
+        let contact: Contact = json::decode(&encoded).unwrap();
+
+        assert_eq!(contact.address, address);
+        assert_eq!(contact.failures, 0);
+        assert_eq!(contact.last_contact, None);
+        assert!(contact.added > 0);
+    }
+}
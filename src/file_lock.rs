@@ -0,0 +1,100 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+const MAX_ATTEMPTS: u32 = 100;
+
+fn retry_interval() -> Duration {
+    Duration::from_millis(20)
+}
+
+/// A lock file older than this is assumed to have been left behind by a
+/// holder that crashed rather than one that is still legitimately working;
+/// every real use of `FileLock` wraps a single cache read-modify-write, so a
+/// live holder never comes close to this age.
+fn stale_lock_age() -> Duration {
+    Duration::from_secs(30)
+}
+
+/// An advisory lock on a file, used to serialize the read-modify-write of
+/// the bootstrap cache across processes that share a working directory.
+///
+/// This only protects cooperating callers: it works by creating a sibling
+/// `<path>.lock` file and deleting it on drop, so another process that
+/// doesn't go through `FileLock::acquire` can still race the cache file. A
+/// lock file left behind by a holder that crashed before it could be
+/// removed is detected by its age and broken rather than wedging every
+/// future `acquire` against it forever.
+pub struct FileLock {
+    lock_path: PathBuf,
+}
+
+impl FileLock {
+    /// Blocks until the lock for `path` is acquired, retrying with a short
+    /// backoff if another process currently holds it, and breaking the lock
+    /// outright if it looks abandoned. Gives up with the `AlreadyExists`
+    /// error once `MAX_ATTEMPTS` retries have failed.
+    pub fn acquire(path: &Path) -> io::Result<FileLock> {
+        let mut lock_name: OsString = path.as_os_str().to_owned();
+        lock_name.push(".lock");
+        let lock_path = PathBuf::from(lock_name);
+
+        let mut attempt = 0;
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&lock_path) {
+                Ok(_) => return Ok(FileLock { lock_path: lock_path }),
+                Err(e) => {
+                    if e.kind() != io::ErrorKind::AlreadyExists {
+                        return Err(e);
+                    }
+                    if FileLock::is_stale(&lock_path) {
+                        // Best-effort: if another process wins the race to
+                        // remove and recreate it first, our next create_new
+                        // just sees a fresh lock and falls through to the
+                        // normal retry-with-backoff path below.
+                        let _ = fs::remove_file(&lock_path);
+                        continue;
+                    }
+                    if attempt >= MAX_ATTEMPTS {
+                        return Err(e);
+                    }
+                    attempt += 1;
+                    thread::sleep(retry_interval());
+                }
+            }
+        }
+    }
+
+    fn is_stale(lock_path: &Path) -> bool {
+        fs::metadata(lock_path)
+            .and_then(|meta| meta.modified())
+            .map(|modified| modified.elapsed().map(|age| age > stale_lock_age()).unwrap_or(false))
+            .unwrap_or(false)
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.lock_path);
+    }
+}
@@ -0,0 +1,301 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use transport::Endpoint;
+
+/// Broad classification of an IP address's routing scope.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum IpClass {
+    /// Globally routable on the public Internet.
+    Global,
+    /// RFC 1918 or RFC 6598 shared (IPv4), or unique local (IPv6) address
+    /// space.
+    Private,
+    Loopback,
+    LinkLocal,
+    /// Multicast, unspecified, or otherwise set aside.
+    Reserved,
+}
+
+pub fn classify(ip: &IpAddr) -> IpClass {
+    match *ip {
+        IpAddr::V4(ref v4) => classify_v4(v4),
+        IpAddr::V6(ref v6) => classify_v6(v6),
+    }
+}
+
+fn classify_v4(ip: &Ipv4Addr) -> IpClass {
+    let o = ip.octets();
+    if o[0] == 127 {
+        IpClass::Loopback
+    } else if o[0] == 0 {
+        IpClass::Reserved
+    } else if o[0] == 10 {
+        IpClass::Private
+    } else if o[0] == 172 && o[1] >= 16 && o[1] <= 31 {
+        IpClass::Private
+    } else if o[0] == 192 && o[1] == 168 {
+        IpClass::Private
+    } else if o[0] == 100 && o[1] >= 64 && o[1] <= 127 {
+        // 100.64.0.0/10, RFC 6598 shared address space used for carrier-grade
+        // NAT: not privately assigned, but never globally routable either.
+        IpClass::Private
+    } else if o[0] == 192 && o[1] == 0 && o[2] == 2 {
+        IpClass::Reserved // 192.0.2.0/24, documentation (TEST-NET-1).
+    } else if o[0] == 198 && o[1] == 51 && o[2] == 100 {
+        IpClass::Reserved // 198.51.100.0/24, documentation (TEST-NET-2).
+    } else if o[0] == 203 && o[1] == 0 && o[2] == 113 {
+        IpClass::Reserved // 203.0.113.0/24, documentation (TEST-NET-3).
+    } else if o[0] == 198 && (o[1] == 18 || o[1] == 19) {
+        IpClass::Reserved // 198.18.0.0/15, benchmarking.
+    } else if o[0] == 169 && o[1] == 254 {
+        IpClass::LinkLocal
+    } else if o[0] >= 224 {
+        // 224-239 multicast, 240-255 reserved/experimental.
+        IpClass::Reserved
+    } else {
+        IpClass::Global
+    }
+}
+
+fn classify_v6(ip: &Ipv6Addr) -> IpClass {
+    if *ip == Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1) {
+        return IpClass::Loopback;
+    }
+    if *ip == Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0) {
+        return IpClass::Reserved;
+    }
+    let segments = ip.segments();
+    // IPv4-mapped (::ffff:a.b.c.d) and IPv4-compatible (::a.b.c.d) addresses
+    // embed an IPv4 address in the low 32 bits; classify on the embedded
+    // address instead of falling through to Global, or a private/loopback
+    // IPv4 address wrapped as v6 would silently bypass the filter.
+    if segments[0] == 0 && segments[1] == 0 && segments[2] == 0 && segments[3] == 0 &&
+       segments[4] == 0 && (segments[5] == 0 || segments[5] == 0xffff) {
+        let embedded = Ipv4Addr::new((segments[6] >> 8) as u8, segments[6] as u8,
+                                      (segments[7] >> 8) as u8, segments[7] as u8);
+        return classify_v4(&embedded);
+    }
+    let first = segments[0];
+    if (first & 0xfe00) == 0xfc00 {
+        // fc00::/7, unique local addresses.
+        IpClass::Private
+    } else if (first & 0xffc0) == 0xfe80 {
+        // fe80::/10, link-local.
+        IpClass::LinkLocal
+    } else if (first & 0xff00) == 0xff00 {
+        // ff00::/8, multicast.
+        IpClass::Reserved
+    } else {
+        IpClass::Global
+    }
+}
+
+/// A CIDR block, used by `IpPolicy::Custom` to allow- or deny-list address
+/// ranges explicitly.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Cidr {
+    pub network: IpAddr,
+    pub prefix_len: u8,
+}
+
+impl Cidr {
+    pub fn new(network: IpAddr, prefix_len: u8) -> Cidr {
+        Cidr { network: network, prefix_len: prefix_len }
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, *ip) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let prefix = self.prefix_len.min(32);
+                let mask: u32 = if prefix == 0 { 0 } else { !0u32 << (32 - prefix) };
+                (u32::from(net) & mask) == (u32::from(addr) & mask)
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                Cidr::segments_match(net.segments(), addr.segments(), self.prefix_len.min(128))
+            }
+            _ => false,
+        }
+    }
+
+    fn segments_match(a: [u16; 8], b: [u16; 8], prefix_len: u8) -> bool {
+        let mut bits_left = prefix_len;
+        for i in 0..8 {
+            if bits_left == 0 {
+                break;
+            }
+            let take = if bits_left >= 16 { 16 } else { bits_left };
+            let mask: u16 = if take == 0 { 0 } else { !0u16 << (16 - take) };
+            if (a[i] & mask) != (b[i] & mask) {
+                return false;
+            }
+            bits_left -= take;
+        }
+        true
+    }
+}
+
+/// Determines which endpoints are acceptable bootstrap contacts.
+#[derive(Debug, Clone)]
+pub enum IpPolicy {
+    /// No filtering; every address is acceptable.
+    AllowAll,
+    /// Only globally-routable addresses are acceptable. The safe default.
+    PublicOnly,
+    /// Only private/loopback/link-local addresses are acceptable, for
+    /// operators running entirely on a LAN.
+    PrivateOnly,
+    /// Explicit allow/deny CIDR lists. An address is accepted if it matches
+    /// no deny entry, and either the allow list is empty or it matches an
+    /// allow entry.
+    Custom { allow: Vec<Cidr>, deny: Vec<Cidr> },
+}
+
+/// Applies an `IpPolicy` to bootstrap endpoints, both when they are about to
+/// be cached and when they are read back out.
+#[derive(Debug, Clone)]
+pub struct IpFilter {
+    policy: IpPolicy,
+}
+
+impl IpFilter {
+    pub fn new(policy: IpPolicy) -> IpFilter {
+        IpFilter { policy: policy }
+    }
+
+    /// Public-only filtering: the safe default for a node bootstrapping
+    /// over the public Internet.
+    pub fn default_policy() -> IpFilter {
+        IpFilter::new(IpPolicy::PublicOnly)
+    }
+
+    pub fn allows_ip(&self, ip: &IpAddr) -> bool {
+        match self.policy {
+            IpPolicy::AllowAll => true,
+            IpPolicy::PublicOnly => classify(ip) == IpClass::Global,
+            IpPolicy::PrivateOnly => classify(ip) != IpClass::Global && classify(ip) != IpClass::Reserved,
+            IpPolicy::Custom { ref allow, ref deny } => {
+                if deny.iter().any(|cidr| cidr.contains(ip)) {
+                    return false;
+                }
+                allow.is_empty() || allow.iter().any(|cidr| cidr.contains(ip))
+            }
+        }
+    }
+
+    pub fn allows_socket_addr(&self, addr: &SocketAddr) -> bool {
+        self.allows_ip(&addr.ip())
+    }
+
+    pub fn allows_endpoint(&self, endpoint: &Endpoint) -> bool {
+        self.allows_socket_addr(&endpoint.socket_addr())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4};
+    use transport::Endpoint;
+
+    #[test]
+    fn classifies_ipv4_ranges() {
+        assert_eq!(classify(&IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8))), IpClass::Global);
+        assert_eq!(classify(&IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))), IpClass::Private);
+        assert_eq!(classify(&IpAddr::V4(Ipv4Addr::new(172, 16, 0, 1))), IpClass::Private);
+        assert_eq!(classify(&IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1))), IpClass::Private);
+        assert_eq!(classify(&IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))), IpClass::Loopback);
+        assert_eq!(classify(&IpAddr::V4(Ipv4Addr::new(169, 254, 1, 1))), IpClass::LinkLocal);
+        assert_eq!(classify(&IpAddr::V4(Ipv4Addr::new(224, 0, 0, 1))), IpClass::Reserved);
+        assert_eq!(classify(&IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0))), IpClass::Reserved);
+        assert_eq!(classify(&IpAddr::V4(Ipv4Addr::new(100, 64, 0, 1))), IpClass::Private);
+        assert_eq!(classify(&IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))), IpClass::Reserved);
+        assert_eq!(classify(&IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1))), IpClass::Reserved);
+        assert_eq!(classify(&IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1))), IpClass::Reserved);
+        assert_eq!(classify(&IpAddr::V4(Ipv4Addr::new(198, 18, 0, 1))), IpClass::Reserved);
+    }
+
+    #[test]
+    fn classifies_ipv6_ranges() {
+        assert_eq!(classify(&IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1))), IpClass::Global);
+        assert_eq!(classify(&IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1))), IpClass::Private);
+        assert_eq!(classify(&IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1))), IpClass::LinkLocal);
+        assert_eq!(classify(&IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1))), IpClass::Loopback);
+        assert_eq!(classify(&IpAddr::V6(Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1))), IpClass::Reserved);
+    }
+
+    #[test]
+    fn classifies_ipv4_mapped_and_compatible_ipv6_on_the_embedded_address() {
+        // ::ffff:10.0.0.1, IPv4-mapped.
+        assert_eq!(classify(&IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0xffff, 0x0a00, 1))),
+                   IpClass::Private);
+        // ::ffff:127.0.0.1, IPv4-mapped.
+        assert_eq!(classify(&IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0xffff, 0x7f00, 1))),
+                   IpClass::Loopback);
+        // ::10.0.0.1, IPv4-compatible (deprecated, but must not silently pass as Global).
+        assert_eq!(classify(&IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0x0a00, 1))),
+                   IpClass::Private);
+        // ::ffff:8.8.8.8, IPv4-mapped public address stays Global.
+        assert_eq!(classify(&IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0xffff, 0x0808, 0x0808))),
+                   IpClass::Global);
+    }
+
+    #[test]
+    fn cidr_contains_checks_the_prefix_only() {
+        let cidr = Cidr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 8);
+        assert!(cidr.contains(&IpAddr::V4(Ipv4Addr::new(10, 1, 2, 3))));
+        assert!(!cidr.contains(&IpAddr::V4(Ipv4Addr::new(11, 0, 0, 1))));
+
+        let cidr6 = Cidr::new(IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0)), 32);
+        assert!(cidr6.contains(&IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 1, 2, 3, 4, 5, 6))));
+        assert!(!cidr6.contains(&IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb9, 0, 0, 0, 0, 0, 0))));
+    }
+
+    #[test]
+    fn public_only_policy_rejects_private_and_reserved() {
+        let filter = IpFilter::default_policy();
+        assert!(filter.allows_ip(&IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8))));
+        assert!(!filter.allows_ip(&IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))));
+        assert!(!filter.allows_ip(&IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))));
+    }
+
+    #[test]
+    fn private_only_policy_accepts_private_and_loopback_but_not_global() {
+        let filter = IpFilter::new(IpPolicy::PrivateOnly);
+        assert!(filter.allows_ip(&IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1))));
+        assert!(filter.allows_ip(&IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))));
+        assert!(!filter.allows_ip(&IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8))));
+    }
+
+    #[test]
+    fn custom_policy_applies_deny_before_allow() {
+        let allow = vec![Cidr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 8)];
+        let deny = vec![Cidr::new(IpAddr::V4(Ipv4Addr::new(10, 1, 0, 0)), 16)];
+        let filter = IpFilter::new(IpPolicy::Custom { allow: allow, deny: deny });
+        assert!(filter.allows_ip(&IpAddr::V4(Ipv4Addr::new(10, 2, 0, 1))));
+        assert!(!filter.allows_ip(&IpAddr::V4(Ipv4Addr::new(10, 1, 0, 1))));
+        assert!(!filter.allows_ip(&IpAddr::V4(Ipv4Addr::new(11, 0, 0, 1))));
+    }
+
+    #[test]
+    fn allows_endpoint_delegates_to_allows_ip() {
+        let filter = IpFilter::default_policy();
+        let endpoint = Endpoint::Tcp(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(8, 8, 8, 8), 80)));
+        assert!(filter.allows_endpoint(&endpoint));
+    }
+}
@@ -0,0 +1,47 @@
+// Copyright 2015 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.0.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+/// A single addressable transport endpoint for a peer.
+///
+/// Only TCP is wired up today; the variant exists so callers don't need to
+/// special-case the transport kind when storing or comparing endpoints.
+#[derive(RustcEncodable, RustcDecodable, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Endpoint {
+    Tcp(SocketAddr),
+}
+
+impl Endpoint {
+    pub fn socket_addr(&self) -> SocketAddr {
+        match *self {
+            Endpoint::Tcp(addr) => addr,
+        }
+    }
+}
+
+/// Attempts a short-lived connection to `endpoint`, purely to establish
+/// whether it is currently reachable. Used by the bootstrap cache's
+/// liveness probe; callers that want an actual session should go through
+/// the full connection-management layer instead.
+pub fn connect(endpoint: &Endpoint, timeout: Duration) -> io::Result<()> {
+    match *endpoint {
+        Endpoint::Tcp(addr) => TcpStream::connect_timeout(&addr, timeout).map(|_| ()),
+    }
+}